@@ -1,10 +1,61 @@
-extern crate crowbook_localize;
-use crowbook_localize::Localizer;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// Shared with `src/lib/localize.rs`'s own `extract_pot_template` entry
+// point: regenerating `po/crowbook.pot` on every build, not just on demand,
+// is how this repo keeps the template in sync with `lformat!` call sites.
+include!("src/lib/pot_extract.rs");
 
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
-    println!("cargo:rerun-if-changed=lang/fr.mo");
-    let mut localizer = Localizer::new();
-    localizer.add_lang("fr", include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/lang/fr.mo"))).unwrap();
-    localizer.write_macro_file(concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib/localize_macros.rs")).unwrap();
-}
\ No newline at end of file
+    println!("cargo:rerun-if-changed=lang");
+    println!("cargo:rerun-if-changed=src");
+
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+
+    write_lang_registry(&manifest_dir);
+    write_pot_template(&manifest_dir);
+}
+
+/// Generates `src/lib/lang_registry.rs`, bundling every `.mo` file found in
+/// `lang/` so `localize::Catalogs::with_bundled_catalogs` can select among
+/// them at runtime (the active one being picked via `localize::init_from_book`).
+fn write_lang_registry(manifest_dir: &str) {
+    let lang_dir = Path::new(manifest_dir).join("lang");
+    let mut registry = String::new();
+    registry.push_str("// @generated by build.rs: bundled runtime translation catalogs. Do not edit by hand.\n");
+    registry.push_str("pub const BUNDLED_CATALOGS: &[(&str, &[u8])] = &[\n");
+    if let Ok(entries) = fs::read_dir(&lang_dir) {
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("mo") {
+                continue;
+            }
+            let lang = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(lang) => lang,
+                None => continue,
+            };
+            registry.push_str(&format!(
+                "    ({:?}, include_bytes!({:?})),\n",
+                lang,
+                path.display()
+            ));
+        }
+    }
+    registry.push_str("];\n");
+    fs::write(Path::new(manifest_dir).join("src/lib/lang_registry.rs"), registry)
+        .expect("could not write src/lib/lang_registry.rs");
+}
+
+/// Regenerates `po/crowbook.pot` from every `lformat!` call site under
+/// `src/`, so new locales can always be started from an up-to-date template.
+fn write_pot_template(manifest_dir: &str) {
+    let src_dir = Path::new(manifest_dir).join("src");
+    let po_dir = Path::new(manifest_dir).join("po");
+    fs::create_dir_all(&po_dir).expect("could not create po/");
+    let pot_file = po_dir.join("crowbook.pot");
+    if let Err(err) = extract_pot_template(&src_dir, &pot_file) {
+        println!("cargo:warning=could not regenerate po/crowbook.pot: {}", err);
+    }
+}
@@ -0,0 +1,280 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Caribon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Runtime translation catalogs for `lformat!`.
+//!
+//! `Catalogs` loads one or more compiled `.mo` catalogs at startup and lets
+//! the `lformat!` macro (defined in this module) resolve against whichever
+//! one is currently active, selected from the book's `lang` option via
+//! `init_from_book`/`set_active_lang`. `init_from_book` also loads any extra
+//! catalogs found in the book's `rendering.lang_dir` option, on top of the
+//! catalogs bundled at build time.
+//!
+//! `lib.rs` must declare this module with `#[macro_use] mod localize;`,
+//! before `mod syntax;`/`mod logger;` and any other module that calls
+//! `lformat!`, so the macro is in scope for them.
+
+use book::Book;
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::{OnceLock, RwLock};
+
+// Generated by build.rs: `pub const BUNDLED_CATALOGS: &[(&str, &[u8])]`,
+// one entry per `.mo` file found in `lang/` at build time.
+include!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib/lang_registry.rs"));
+
+// Shared with build.rs's own `.pot` regeneration step: `extract_pot_template`
+// and its helpers.
+include!(concat!(env!("CARGO_MANIFEST_DIR"), "/src/lib/pot_extract.rs"));
+
+/// A single parsed `.mo` catalog: original string -> translated string.
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    messages: HashMap<String, String>,
+}
+
+/// Error returned when a `.mo` file can't be parsed.
+#[derive(Debug)]
+pub struct InvalidCatalog(String);
+
+impl fmt::Display for InvalidCatalog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid .mo catalog: {}", self.0)
+    }
+}
+
+fn read_u32(data: &[u8], pos: usize, little_endian: bool) -> Option<u32> {
+    if pos + 4 > data.len() {
+        return None;
+    }
+    let b = &data[pos..pos + 4];
+    Some(if little_endian {
+        (b[0] as u32) | ((b[1] as u32) << 8) | ((b[2] as u32) << 16) | ((b[3] as u32) << 24)
+    } else {
+        ((b[0] as u32) << 24) | ((b[1] as u32) << 16) | ((b[2] as u32) << 8) | (b[3] as u32)
+    })
+}
+
+impl Catalog {
+    /// Parses the binary content of a gettext `.mo` file.
+    pub fn from_mo_bytes(data: &[u8]) -> Result<Catalog, InvalidCatalog> {
+        let magic = read_u32(data, 0, true)
+            .ok_or_else(|| InvalidCatalog("file too short".to_owned()))?;
+        let little_endian = match magic {
+            0x9504_12de => true,
+            0xde12_0495 => false,
+            _ => return Err(InvalidCatalog("bad magic number".to_owned())),
+        };
+        let count = read_u32(data, 8, little_endian)
+            .ok_or_else(|| InvalidCatalog("missing string count".to_owned()))? as usize;
+        let orig_table = read_u32(data, 12, little_endian)
+            .ok_or_else(|| InvalidCatalog("missing originals table".to_owned()))? as usize;
+        let trans_table = read_u32(data, 16, little_endian)
+            .ok_or_else(|| InvalidCatalog("missing translations table".to_owned()))? as usize;
+
+        let mut messages = HashMap::with_capacity(count);
+        for i in 0..count {
+            let o_len = read_u32(data, orig_table + i * 8, little_endian)
+                .ok_or_else(|| InvalidCatalog("truncated originals table".to_owned()))? as usize;
+            let o_off = read_u32(data, orig_table + i * 8 + 4, little_endian)
+                .ok_or_else(|| InvalidCatalog("truncated originals table".to_owned()))? as usize;
+            let t_len = read_u32(data, trans_table + i * 8, little_endian)
+                .ok_or_else(|| InvalidCatalog("truncated translations table".to_owned()))? as usize;
+            let t_off = read_u32(data, trans_table + i * 8 + 4, little_endian)
+                .ok_or_else(|| InvalidCatalog("truncated translations table".to_owned()))? as usize;
+            if o_off + o_len > data.len() || t_off + t_len > data.len() {
+                return Err(InvalidCatalog("string out of bounds".to_owned()));
+            }
+            let original = String::from_utf8_lossy(&data[o_off..o_off + o_len]).into_owned();
+            let translation = String::from_utf8_lossy(&data[t_off..t_off + t_len]).into_owned();
+            if !original.is_empty() && !translation.is_empty() {
+                messages.insert(original, translation);
+            }
+        }
+        Ok(Catalog { messages })
+    }
+
+    /// Looks up `msgid`'s translation, if present in this catalog.
+    pub fn get(&self, msgid: &str) -> Option<&str> {
+        self.messages.get(msgid).map(String::as_str)
+    }
+}
+
+/// Holds every loaded catalog and picks which one `lformat!` resolves against.
+#[derive(Debug, Default)]
+pub struct Catalogs {
+    catalogs: HashMap<String, Catalog>,
+    active: Option<String>,
+}
+
+impl Catalogs {
+    /// Creates an empty `Catalogs`, with no catalog loaded (`lformat!` will
+    /// fall back to the untranslated, English source strings).
+    pub fn new() -> Catalogs {
+        Catalogs::default()
+    }
+
+    /// Creates a `Catalogs` pre-loaded with every catalog bundled at build
+    /// time from the `lang/` directory (see `build.rs`). This is the starting
+    /// point used by the CLI; embedders who only care about specific
+    /// languages can start from `new()` and call `add_catalog`/`load_dir`
+    /// instead.
+    pub fn with_bundled_catalogs() -> Catalogs {
+        let mut catalogs = Catalogs::new();
+        for &(lang, data) in BUNDLED_CATALOGS {
+            // Bundled catalogs are generated from known-good .mo files at
+            // build time, so a parse failure here would be a build.rs bug.
+            catalogs.add_catalog(lang, data).ok();
+        }
+        catalogs
+    }
+
+    /// Parses `data` as a `.mo` catalog and registers it under `lang`'s base
+    /// subtag (e.g. `"fr_FR"` and `"fr"` both register under `"fr"`),
+    /// replacing any catalog previously loaded for that language.
+    pub fn add_catalog(&mut self, lang: &str, data: &[u8]) -> Result<(), InvalidCatalog> {
+        let catalog = Catalog::from_mo_bytes(data)?;
+        self.catalogs.insert(normalize_lang(lang), catalog);
+        Ok(())
+    }
+
+    /// Loads every `<lang>.mo` file found directly in `dir`, registering each
+    /// one under its file stem. Used both for the bundled `lang/` directory
+    /// and for any user-supplied catalog directory.
+    pub fn load_dir<P: AsRef<Path>>(&mut self, dir: P) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension() != Some(OsStr::new("mo")) {
+                continue;
+            }
+            let lang = match path.file_stem().and_then(OsStr::to_str) {
+                Some(lang) => lang.to_owned(),
+                None => continue,
+            };
+            let mut data = vec![];
+            File::open(&path)?.read_to_end(&mut data)?;
+            if self.add_catalog(&lang, &data).is_err() {
+                // Not a valid .mo file: skip it rather than failing startup.
+                continue;
+            }
+        }
+        Ok(())
+    }
+
+    /// Selects the active language (e.g. the book's `lang` option, like
+    /// `"fr"` or `"fr_FR"`). Falls back to English (no translation) if no
+    /// catalog was loaded for it.
+    pub fn set_active_lang(&mut self, lang: &str) {
+        let lang = normalize_lang(lang);
+        self.active = if self.catalogs.contains_key(&lang) {
+            Some(lang)
+        } else {
+            None
+        };
+    }
+
+    /// Translates `msgid` against the active catalog, falling back to
+    /// `msgid` itself (the English source string) if there is no active
+    /// catalog or it has no entry for this string.
+    pub fn gettext<'a>(&'a self, msgid: &'a str) -> &'a str {
+        self.active
+            .as_ref()
+            .and_then(|lang| self.catalogs.get(lang))
+            .and_then(|catalog| catalog.get(msgid))
+            .unwrap_or(msgid)
+    }
+}
+
+/// Normalizes a language tag down to its base subtag (`"fr_FR"`, `"fr.UTF-8"`
+/// -> `"fr"`), used as the catalog key on both the load and the lookup side
+/// so a region- or encoding-qualified `lang` still finds its catalog.
+fn normalize_lang(lang: &str) -> String {
+    lang.split(|c| c == '_' || c == '.').next().unwrap_or(lang).to_owned()
+}
+
+static GLOBAL_CATALOGS: OnceLock<RwLock<Catalogs>> = OnceLock::new();
+
+/// The process-wide catalog set `lformat!` resolves against, lazily
+/// initialized with every catalog bundled at build time.
+fn global_catalogs() -> &'static RwLock<Catalogs> {
+    GLOBAL_CATALOGS.get_or_init(|| RwLock::new(Catalogs::with_bundled_catalogs()))
+}
+
+/// Selects the process-wide active language for every subsequent `lformat!`
+/// call (see `Catalogs::set_active_lang`).
+pub fn set_active_lang(lang: &str) {
+    global_catalogs().write().unwrap().set_active_lang(lang);
+}
+
+/// Loads every `.mo` catalog found in `dir` into the process-wide catalog
+/// set, on top of (and overriding, for matching languages) the catalogs
+/// bundled at build time. Errors (e.g. a missing or unreadable directory)
+/// are reported through `book.logger` and otherwise ignored.
+pub fn load_dir_from_book(book: &Book, dir: &str) {
+    if let Err(err) = global_catalogs().write().unwrap().load_dir(dir) {
+        book.logger.for_category("localize").error(lformat!(
+            "could not load custom translation catalogs from {dir}: {error}",
+            dir = dir,
+            error = err
+        ));
+    }
+}
+
+/// Reads `book`'s `rendering.lang_dir` and `lang` options and applies them:
+/// loads any extra `.mo` catalogs found in `rendering.lang_dir` (so book
+/// authors can ship or override a translation without rebuilding crowbook),
+/// then makes `lang` the active language for `lformat!`. Called once a
+/// book's configuration has been loaded; falls back to leaving the current
+/// language/catalogs untouched if the corresponding option isn't set.
+pub fn init_from_book(book: &Book) {
+    if let Ok(dir) = book.options.get_str("rendering.lang_dir") {
+        load_dir_from_book(book, dir);
+    }
+    if let Ok(lang) = book.options.get_str("lang") {
+        set_active_lang(lang);
+    }
+}
+
+/// Translates `msgid` against the process-wide active catalog, then expands
+/// `{name}` placeholders from `args`, e.g. `translate("hello {who}", &[("who",
+/// "world".to_owned())])`. This is what the `lformat!` macro expands to.
+pub fn translate(msgid: &str, args: &[(&str, String)]) -> String {
+    let mut result = global_catalogs().read().unwrap().gettext(msgid).to_owned();
+    for &(name, ref value) in args {
+        result = result.replace(&format!("{{{}}}", name), value);
+    }
+    result
+}
+
+/// Translates a format string against the currently active runtime catalog
+/// (see `Catalogs`/`init_from_book`), then substitutes any named arguments,
+/// e.g. `lformat!("hello {who}", who = name)`.
+#[macro_export]
+macro_rules! lformat {
+    ($fmt:expr) => {
+        $crate::localize::translate($fmt, &[])
+    };
+    ($fmt:expr, $($name:ident = $val:expr),+ $(,)*) => {
+        $crate::localize::translate($fmt, &[$((stringify!($name), format!("{}", $val))),+])
+    };
+}
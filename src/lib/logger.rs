@@ -1,9 +1,41 @@
 use term;
+#[cfg(feature = "log-facade")]
+use log;
 
 use std::convert::AsRef;
+use std::env;
 use std::io;
 use std::io::Write;
 use std::fmt::Display;
+use std::rc::Rc;
+
+/// Name of the environment variable used to set per-category verbosity directives.
+const LOG_ENV_VAR: &'static str = "CROWBOOK_LOG";
+
+/// Name of the environment variable used to select the JSON log output mode.
+const LOG_FORMAT_ENV_VAR: &'static str = "CROWBOOK_LOG_FORMAT";
+
+/// Whether `CROWBOOK_LOG_FORMAT` requests JSON output.
+fn json_output_enabled() -> bool {
+    env::var(LOG_FORMAT_ENV_VAR).map(|v| v == "json").unwrap_or(false)
+}
+
+/// Minimal JSON string escaping for the structured log output mode.
+fn escape_json(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
 
 /// The level of information to display to a logger
 ///
@@ -30,15 +62,62 @@ pub enum InfoLevel {
 
 use self::InfoLevel::*;
 
-/// Abstract over either term output or (if it fails) io::stderr()
+#[cfg(feature = "log-facade")]
+impl InfoLevel {
+    /// Maps to the corresponding level of the `log` crate.
+    fn to_log_level(self) -> log::Level {
+        match self {
+            Debug => log::Level::Debug,
+            Warning => log::Level::Warn,
+            Info => log::Level::Info,
+            Error => log::Level::Error,
+            Quiet | __NonExhaustive => unreachable!(),
+        }
+    }
+}
+
+impl InfoLevel {
+    /// Returns a stable, lowercase name for this level, e.g. for the `level`
+    /// field of the JSON log output mode.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Debug => "debug",
+            Warning => "warning",
+            Info => "info",
+            Error => "error",
+            Quiet => "quiet",
+            __NonExhaustive => unreachable!(),
+        }
+    }
+}
+
+/// Abstract over either term output, io::stderr(), or a structured JSON
+/// backend. With the `log-facade` feature, messages are always forwarded to
+/// the `log` crate instead, so only the `Stderr` variant is ever needed.
+#[cfg(not(feature = "log-facade"))]
 enum Output {
     Terminal(Box<term::StderrTerminal>),
     Stderr(io::Stderr),
+    Json(io::Stderr),
 }
 
+#[cfg(feature = "log-facade")]
+enum Output {
+    Stderr(io::Stderr),
+}
+
+#[cfg(not(feature = "log-facade"))]
 impl Output {
-    /// Creates a new Output
+    /// Creates a new Output, picking JSON mode if `CROWBOOK_LOG_FORMAT=json` is set.
     pub fn new() -> Output {
+        Self::new_with_mode(json_output_enabled())
+    }
+
+    /// Creates a new Output, forcing JSON output on or off.
+    pub fn new_with_mode(json: bool) -> Output {
+        if json {
+            return Output::Json(io::stderr());
+        }
         if let Some(term) = term::stderr() {
             if (*term).supports_color() {
                 return Output::Terminal(term)
@@ -47,8 +126,21 @@ impl Output {
         Output::Stderr(io::stderr())
     }
 
-    /// Print a msg prefixed by a coloured `level` message
-    pub fn print_msg<S: Display>(&mut self, level: InfoLevel, msg: S) {
+    /// Print a msg, either as a coloured `level`-prefixed line or, in JSON mode,
+    /// as a single `{"level": ..., "category": ..., "message": ...}` object.
+    /// `category` is only non-empty for loggers tagged via `Logger::for_category`
+    /// (e.g. `"syntax"` from `syntax.rs`); an untagged `Logger` serializes `""`.
+    pub fn print_msg<S: Display>(&mut self, level: InfoLevel, category: Option<&str>, msg: S) {
+        if let Output::Json(ref mut stderr) = *self {
+            writeln!(stderr,
+                     "{{\"level\":\"{level}\",\"category\":\"{category}\",\"message\":\"{msg}\"}}",
+                     level = level.as_str(),
+                     category = escape_json(category.unwrap_or("")),
+                     msg = escape_json(&msg.to_string()))
+                .unwrap();
+            return;
+        }
+
         let (colour, head_msg) = match level {
             Debug => (term::color::BRIGHT_BLUE, lformat!("Debug: ")),
             Warning => (term::color::BRIGHT_YELLOW, lformat!("Warning: ")),
@@ -74,10 +166,68 @@ impl Output {
                        msg)
                     .unwrap();
             }
+            Output::Json(_) => unreachable!(),
         }
     }
 }
 
+/// With the `log-facade` feature, `Output` forwards every message to the
+/// `log` crate instead of writing to the terminal itself, so library users
+/// can plug in their own backend (`env_logger`, a `tracing` bridge, etc.).
+#[cfg(feature = "log-facade")]
+impl Output {
+    /// Creates a new Output
+    pub fn new() -> Output {
+        Output::Stderr(io::stderr())
+    }
+
+    /// Creates a new Output (JSON mode is a no-op here, the `log` crate's own
+    /// backend is responsible for how messages are formatted)
+    pub fn new_with_mode(_json: bool) -> Output {
+        Self::new()
+    }
+
+    /// Forwards a msg to the `log` crate at the matching level, tagging it
+    /// with `category` as the log record's target
+    pub fn print_msg<S: Display>(&mut self, level: InfoLevel, category: Option<&str>, msg: S) {
+        log::log!(target: category.unwrap_or("crowbook"), level.to_log_level(), "{}", msg);
+    }
+}
+
+/// Parses a single directive's level, e.g. "debug" or "warning".
+fn parse_level(s: &str) -> Option<InfoLevel> {
+    match s.trim().to_lowercase().as_str() {
+        "debug" => Some(Debug),
+        "warning" | "warn" => Some(Warning),
+        "info" => Some(Info),
+        "error" => Some(Error),
+        "quiet" | "off" => Some(Quiet),
+        _ => None,
+    }
+}
+
+/// Parses a `CROWBOOK_LOG`-style spec (e.g. `html=debug,latex=warning,error`) into
+/// an ordered list of `(target, level)` rules, a bare `level` setting the default
+/// (untargeted) rule. Unparseable directives are silently ignored.
+fn parse_directives(spec: &str) -> Vec<(Option<String>, InfoLevel)> {
+    let mut directives = vec![];
+    for part in spec.split(',') {
+        let part = part.trim();
+        if part.is_empty() {
+            continue;
+        }
+        if let Some(pos) = part.find('=') {
+            let target = part[..pos].trim();
+            if let Some(level) = parse_level(&part[pos + 1..]) {
+                directives.push((Some(target.to_owned()), level));
+            }
+        } else if let Some(level) = parse_level(part) {
+            directives.push((None, level));
+        }
+    }
+    directives
+}
+
 /// Logs info and warning message and choose whether to display them
 /// according to verbosity.
 ///
@@ -88,6 +238,13 @@ impl Output {
 /// * `Logger::display_{level}`: will print the message in any case, since they are static
 ///   methods and don't depend on the current verbosity.
 ///
+/// The verbosity for a given message can also be controlled per-category through the
+/// `CROWBOOK_LOG` environment variable, a comma-separated list of `target=level`
+/// directives (e.g. `html=debug,latex=warning,error`), where a bare `level` with no
+/// target sets the default. A `Logger` tagged with a category (see `for_category`)
+/// picks the most specific directive whose target is a prefix of that category,
+/// falling back to the default directive, then to `set_verbosity`'s level.
+///
 /// # Example
 ///
 /// ```
@@ -101,33 +258,125 @@ impl Output {
 ///
 /// # See also
 /// * `InfoLevel`
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Logger {
     verbosity: InfoLevel,
+    directives: Rc<Vec<(Option<String>, InfoLevel)>>,
+    category: Option<String>,
+    json: Option<bool>,
 }
 
 
 impl Logger {
     /// Creates a new logger with default verbosity (`Info`).
+    ///
+    /// Also parses the `CROWBOOK_LOG` environment variable, if set, to set up
+    /// per-category verbosity directives.
     pub fn new() -> Logger {
-        Logger { verbosity: InfoLevel::Warning }
+        let directives = env::var(LOG_ENV_VAR)
+            .ok()
+            .map(|spec| parse_directives(&spec))
+            .unwrap_or_default();
+        let logger = Logger {
+            verbosity: InfoLevel::Warning,
+            directives: Rc::new(directives),
+            category: None,
+            json: None,
+        };
+        #[cfg(feature = "log-facade")]
+        logger.sync_max_level();
+        logger
+    }
+
+    /// Returns a copy of this logger tagged with `category`.
+    ///
+    /// `category` is typically the name of the rendering subsystem emitting the
+    /// message (e.g. `"html"`, `"latex"`, `"epub"`); it is matched against the
+    /// targets of `CROWBOOK_LOG` directives to pick the effective verbosity.
+    pub fn for_category<S: Into<String>>(&self, category: S) -> Logger {
+        Logger {
+            category: Some(category.into()),
+            ..self.clone()
+        }
+    }
+
+    /// Forces (or un-forces) the structured JSON log output mode, overriding
+    /// the `CROWBOOK_LOG_FORMAT` environment variable.
+    pub fn set_json_output(&mut self, json: bool) -> &mut Logger {
+        self.json = Some(json);
+        self
+    }
+
+    /// Whether this logger should emit JSON, from `set_json_output` or else
+    /// from `CROWBOOK_LOG_FORMAT`.
+    fn json_mode(&self) -> bool {
+        self.json.unwrap_or_else(json_output_enabled)
     }
 
     /// Get verbosity
     pub fn verbosity(&self) -> InfoLevel {
         self.verbosity
     }
-    
+
     /// Sets verbosity
     pub fn set_verbosity(&mut self, verbosity: InfoLevel) -> &mut Logger {
         self.verbosity = verbosity;
+        #[cfg(feature = "log-facade")]
+        self.sync_max_level();
         self
     }
 
+    /// Raises the `log` crate's global max level to the most permissive level
+    /// this logger could ever emit at, i.e. the lowest of `self.verbosity`
+    /// and every `CROWBOOK_LOG` directive's level. `log`'s own global
+    /// fast-path would otherwise drop messages below the flat `verbosity`
+    /// before `effective_verbosity`'s per-category directives ever get a
+    /// chance to let them through.
+    #[cfg(feature = "log-facade")]
+    fn sync_max_level(&self) {
+        let most_permissive = self.directives
+            .iter()
+            .map(|&(_, level)| level)
+            .fold(self.verbosity, |acc, level| if level < acc { level } else { acc });
+        log::set_max_level(match most_permissive {
+            Debug => log::LevelFilter::Debug,
+            Warning => log::LevelFilter::Warn,
+            Info => log::LevelFilter::Info,
+            Error => log::LevelFilter::Error,
+            Quiet => log::LevelFilter::Off,
+            __NonExhaustive => unreachable!(),
+        });
+    }
+
+    /// Resolves the effective verbosity level for this logger's category,
+    /// picking the most specific matching `CROWBOOK_LOG` directive (longest
+    /// matching target prefix), falling back to the default directive, then
+    /// to the level set by `set_verbosity`.
+    fn effective_verbosity(&self) -> InfoLevel {
+        let mut default = None;
+        let mut best: Option<(usize, InfoLevel)> = None;
+        for &(ref target, level) in self.directives.iter() {
+            match *target {
+                None => default = Some(level),
+                Some(ref target) => {
+                    if let Some(ref category) = self.category {
+                        if category.starts_with(target.as_str())
+                            && best.map_or(true, |(len, _)| target.len() >= len) {
+                            best = Some((target.len(), level));
+                        }
+                    }
+                }
+            }
+        }
+        best.map(|(_, level)| level)
+            .or(default)
+            .unwrap_or(self.verbosity)
+    }
+
     /// Prints a message
     pub fn display_msg<S: AsRef<str>>(level: InfoLevel, s: S) {
         let mut output = Output::new();
-        output.print_msg(level, s.as_ref());
+        output.print_msg(level, None, s.as_ref());
     }
     
     /// Prints a debug message
@@ -151,17 +400,11 @@ impl Logger {
     }
 
 
-    /// Prints a message if logger's verbosity <= level
+    /// Prints a message if logger's (possibly category-specific) verbosity <= level
     pub fn log<S: AsRef<str>>(&self, level: InfoLevel, s: S) {
-        if level >= self.verbosity {
-            match level {
-                Debug => Self::display_debug(s),
-                Info => Self::display_info(s),
-                Warning => Self::display_warning(s),
-                Error => Self::display_error(s),
-                Quiet => unreachable!(),
-                __NonExhaustive => unreachable!(),
-            }
+        if level >= self.effective_verbosity() {
+            let mut output = Output::new_with_mode(self.json_mode());
+            output.print_msg(level, self.category.as_ref().map(String::as_str), s.as_ref());
         }
     }
 
@@ -0,0 +1,105 @@
+// Copyright (C) 2017 Élisabeth HENRY.
+//
+// This file is part of Crowbook.
+//
+// Crowbook is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Lesser General Public License as published
+// by the Free Software Foundation, either version 2.1 of the License, or
+// (at your option) any later version.
+//
+// Caribon is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Lesser General Public License for more details.
+//
+// You should have received ba copy of the GNU Lesser General Public License
+// along with Crowbook.  If not, see <http://www.gnu.org/licenses/>.
+
+//! `.pot` extraction step, shared between `localize::extract_pot_template`
+//! (a runnable library entry point) and `build.rs` (which runs it on every
+//! build so `po/crowbook.pot` always reflects the current `lformat!` call
+//! sites). Kept dependency-free (only `std`) so `build.rs` can `include!`
+//! it directly.
+
+/// Recursively collects the first string literal argument of every
+/// `lformat!(...)` call found in `.rs` files under `dir`.
+fn collect_lformat_calls(dir: &::std::path::Path, msgids: &mut ::std::collections::BTreeSet<String>) -> ::std::io::Result<()> {
+    for entry in ::std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_dir() {
+            collect_lformat_calls(&path, msgids)?;
+        } else if path.extension() == Some(::std::ffi::OsStr::new("rs")) {
+            let mut source = String::new();
+            ::std::io::Read::read_to_string(&mut ::std::fs::File::open(&path)?, &mut source)?;
+            extract_lformat_msgids(&source, msgids);
+        }
+    }
+    Ok(())
+}
+
+/// Extracts the msgids out of every `lformat!("...")` call in `source`.
+fn extract_lformat_msgids(source: &str, msgids: &mut ::std::collections::BTreeSet<String>) {
+    let mut rest = source;
+    while let Some(start) = rest.find("lformat!(") {
+        rest = &rest[start + "lformat!(".len()..];
+        let rest_trimmed = rest.trim_start();
+        if !rest_trimmed.starts_with('"') {
+            continue;
+        }
+        if let Some(msgid) = read_string_literal(rest_trimmed) {
+            msgids.insert(msgid);
+        }
+    }
+}
+
+/// Reads a Rust string literal (including `\"` and `\\` escapes) starting at
+/// the opening quote of `s`, returning its unescaped content.
+fn read_string_literal(s: &str) -> Option<String> {
+    let mut chars = s.char_indices();
+    chars.next(); // Skip the opening quote
+    let mut result = String::new();
+    let mut escaped = false;
+    for (_, c) in chars {
+        if escaped {
+            match c {
+                'n' => result.push('\n'),
+                't' => result.push('\t'),
+                other => result.push(other),
+            }
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            return Some(result);
+        } else {
+            result.push(c);
+        }
+    }
+    None
+}
+
+/// Escapes a string for inclusion in a `.po`/`.pot` `msgid`/`msgstr` line.
+fn escape_po(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Scans every `.rs` file under `src_dir` for `lformat!` invocations and
+/// writes a `.pot` translation template to `pot_file`, one `msgid` per
+/// distinct source string, so new locales can be contributed by running
+/// `msginit`/translating a `.po` file without touching any source code.
+pub fn extract_pot_template(src_dir: &::std::path::Path, pot_file: &::std::path::Path) -> ::std::io::Result<()> {
+    let mut msgids = ::std::collections::BTreeSet::new();
+    collect_lformat_calls(src_dir, &mut msgids)?;
+
+    let mut out = ::std::fs::File::create(pot_file)?;
+    ::std::io::Write::write_fmt(&mut out, format_args!("# Generated by crowbook's lformat! extractor. Do not edit by hand.\n"))?;
+    ::std::io::Write::write_fmt(&mut out, format_args!("msgid \"\"\n"))?;
+    ::std::io::Write::write_fmt(&mut out, format_args!("msgstr \"\"\n"))?;
+    ::std::io::Write::write_fmt(&mut out, format_args!("\"Content-Type: text/plain; charset=UTF-8\\n\"\n\n"))?;
+    for msgid in &msgids {
+        ::std::io::Write::write_fmt(&mut out, format_args!("msgid \"{}\"\n", escape_po(msgid)))?;
+        ::std::io::Write::write_fmt(&mut out, format_args!("msgstr \"\"\n\n"))?;
+    }
+    Ok(())
+}
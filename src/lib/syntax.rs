@@ -37,14 +37,37 @@ pub struct Syntax {}
 #[cfg(feature="syntect")]
 impl Syntax {
     /// Creates a new Syntax wrapper
+    ///
+    /// If set, the book options `rendering.highlight.syntax_dir` and
+    /// `rendering.highlight.theme_dir` point to directories of additional
+    /// `.sublime-syntax` and `.tmTheme` files (respectively) to load on top
+    /// of syntect's bundled defaults, so book authors can use languages and
+    /// color schemes that don't ship with syntect. Invalid paths are
+    /// reported through `book.logger` and otherwise ignored.
     pub fn new(book: &Book, theme_name: &str) -> Syntax {
+        let mut syntax_set = syntect::parsing::SyntaxSet::load_defaults_newlines();
+        if let Ok(dir) = book.options.get_str("rendering.highlight.syntax_dir") {
+            if let Err(err) = syntax_set.load_syntaxes(dir, false) {
+                book.logger.for_category("syntax").error(lformat!("could not load custom syntaxes from {dir}: {error}",
+                                           dir = dir,
+                                           error = err));
+            }
+        }
+
         let mut theme_set = syntect::highlighting::ThemeSet::load_defaults();
+        if let Ok(dir) = book.options.get_str("rendering.highlight.theme_dir") {
+            if let Err(err) = theme_set.add_from_folder(dir) {
+                book.logger.for_category("syntax").error(lformat!("could not load custom themes from {dir}: {error}",
+                                           dir = dir,
+                                           error = err));
+            }
+        }
         let theme = match theme_set.themes.remove(theme_name) {
             Some(theme) => theme,
             None => {
-                book.logger.error(lformat!("could not set syntect theme to {theme}, defaulting to \"InspiredGithub\"",
+                book.logger.for_category("syntax").error(lformat!("could not set syntect theme to {theme}, defaulting to \"InspiredGithub\"",
                                            theme = theme_name));
-                book.logger.info(lformat!("valid theme names are: {themes}",
+                book.logger.for_category("syntax").info(lformat!("valid theme names are: {themes}",
                                           themes = theme_set.themes
                                           .keys()
                                           .map(|s| s.to_owned())
@@ -54,32 +77,39 @@ impl Syntax {
             }
         };
         Syntax {
-            syntax_set: syntect::parsing::SyntaxSet::load_defaults_nonewlines(),
+            syntax_set: syntax_set,
             theme: theme,
         }
     }
-    
-    /// Convert a string containing code to HTML
-    pub fn to_html(&self, code: &str, language: &str) -> Result<String> {
+
+    /// Highlights `code` line-by-line (keeping the highlighter's state across
+    /// lines), so multi-line constructs such as block comments or heredocs
+    /// are coloured correctly across lines.
+    fn highlight<'a>(&'a self, code: &'a str, language: &str) -> Vec<(syntect::highlighting::Style, &'a str)> {
         let language = strip_language(language);
         let syntax = self.syntax_set.find_syntax_by_token(language)
             .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
         let mut h = syntect::easy::HighlightLines::new(syntax, &self.theme);
-        let regions = h.highlight(code);
+        let mut regions = vec![];
+        for line in LinesWithEndings::from(code) {
+            regions.extend(h.highlight(line));
+        }
+        regions
+    }
+
+    /// Convert a string containing code to HTML
+    pub fn to_html(&self, code: &str, language: &str) -> Result<String> {
+        let regions = self.highlight(code, language);
         Ok(format!("<pre>{}</pre>",
                    syntect::html::styles_to_coloured_html(&regions[..],
                                                           syntect::html::IncludeBackground::No)))
     }
 
     pub fn to_tex(&self, code: &str, language: &str) -> Result<String> {
-        let language = strip_language(language);
         use latex::insert_breaks;
         use syntect::highlighting::{BLACK, FONT_STYLE_BOLD, FONT_STYLE_ITALIC, FONT_STYLE_UNDERLINE};
-        let syntax = self.syntax_set.find_syntax_by_token(language)
-            .unwrap_or_else(|| self.syntax_set.find_syntax_plain_text());
-        let mut h = syntect::easy::HighlightLines::new(syntax, &self.theme);
-        let regions = h.highlight(code);
-        
+        let regions = self.highlight(code, language);
+
         let mut result = String::with_capacity(code.len());
         for (style, text) in regions {
             let mut content = escape::tex(text).into_owned();
@@ -112,6 +142,36 @@ impl Syntax {
     }
 }
 
+/// Splits `code` into lines that keep their trailing `\n`, as required by
+/// syntect's newline-aware syntax definitions (a plain `str::lines` would
+/// strip it and confuse the parser's end-of-line rules).
+#[cfg(feature="syntect")]
+struct LinesWithEndings<'a> {
+    input: &'a str,
+}
+
+#[cfg(feature="syntect")]
+impl<'a> LinesWithEndings<'a> {
+    fn from(input: &'a str) -> LinesWithEndings<'a> {
+        LinesWithEndings { input: input }
+    }
+}
+
+#[cfg(feature="syntect")]
+impl<'a> Iterator for LinesWithEndings<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        if self.input.is_empty() {
+            return None;
+        }
+        let split = self.input.find('\n').map(|i| i + 1).unwrap_or_else(|| self.input.len());
+        let (line, rest) = self.input.split_at(split);
+        self.input = rest;
+        Some(line)
+    }
+}
+
 /// Strip language name of possible other infos, e.g. "rust,ignore" -> "rust"
 /// Currently only ',' is done
 fn strip_language(language: &str) -> &str {
@@ -128,7 +188,7 @@ fn strip_language(language: &str) -> &str {
 #[cfg(not(feature="syntect"))]
 impl Syntax {
     pub fn new(book: &Book, _: &str) -> Syntax {
-        book.logger.error(lformat!("crowbook was compiled without syntect support, syntax highlighting will be disabled"));
+        book.logger.for_category("syntax").error(lformat!("crowbook was compiled without syntect support, syntax highlighting will be disabled"));
         Syntax {}
     }
 